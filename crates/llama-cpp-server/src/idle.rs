@@ -0,0 +1,191 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::LocalInferenceBackend;
+
+/// Wraps a `LocalInferenceBackend` with an idle-unload timer: when neither `ensure_running`
+/// nor `touch` has been called for `idle_timeout`, the backend is stopped to free its VRAM, and the next call
+/// transparently restarts it on the same port before proceeding. Restarts are serialized
+/// behind `running` so a burst of concurrent requests only triggers one reload.
+pub(crate) struct IdleGuard {
+    backend: Box<dyn LocalInferenceBackend>,
+    idle_timeout: Option<Duration>,
+    last_used: Mutex<Instant>,
+    running: AsyncMutex<bool>,
+}
+
+impl IdleGuard {
+    pub(crate) fn new(
+        backend: Box<dyn LocalInferenceBackend>,
+        idle_timeout: Option<Duration>,
+    ) -> Arc<Self> {
+        let guard = Arc::new(Self {
+            backend,
+            idle_timeout,
+            last_used: Mutex::new(Instant::now()),
+            running: AsyncMutex::new(true),
+        });
+
+        if let Some(timeout) = guard.idle_timeout {
+            let weak = Arc::downgrade(&guard);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(timeout.max(Duration::from_secs(1)) / 4);
+                loop {
+                    interval.tick().await;
+                    let Some(guard) = weak.upgrade() else {
+                        break;
+                    };
+                    guard.unload_if_idle(timeout).await;
+                }
+            });
+        }
+
+        guard
+    }
+
+    pub(crate) fn port(&self) -> u16 {
+        self.backend.port()
+    }
+
+    pub(crate) fn kind(&self) -> &str {
+        self.backend.kind()
+    }
+
+    /// Marks the backend as just used and restarts it if a prior idle timeout had stopped it
+    /// in the meantime.
+    pub(crate) async fn ensure_running(&self) {
+        self.touch();
+
+        let mut running = self.running.lock().await;
+        if !*running {
+            self.backend.start().await;
+            *running = true;
+        }
+    }
+
+    /// Bumps the last-used timestamp without touching `running`. Called once up front by
+    /// `ensure_running`, and again for each chunk of an in-flight stream, so a generation that
+    /// outlives `idle_timeout` doesn't get stopped out from under it between chunks.
+    pub(crate) fn touch(&self) {
+        *self.last_used.lock().unwrap() = Instant::now();
+    }
+
+    async fn unload_if_idle(&self, timeout: Duration) {
+        if self.last_used.lock().unwrap().elapsed() < timeout {
+            return;
+        }
+
+        // Re-check after acquiring the lock: a concurrent `ensure_running` may have restarted
+        // the backend and bumped `last_used` while we were waiting for `running`, which would
+        // make the idle verdict above stale.
+        let mut running = self.running.lock().await;
+        if *running && self.last_used.lock().unwrap().elapsed() >= timeout {
+            self.backend.stop();
+            *running = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+
+    struct FakeBackend {
+        running: AtomicBool,
+    }
+
+    #[async_trait]
+    impl LocalInferenceBackend for Arc<FakeBackend> {
+        async fn start(&self) {
+            self.running.store(true, Ordering::SeqCst);
+        }
+
+        fn stop(&self) {
+            self.running.store(false, Ordering::SeqCst);
+        }
+
+        fn port(&self) -> u16 {
+            0
+        }
+
+        fn kind(&self) -> &str {
+            "fake"
+        }
+    }
+
+    fn guard_with_stale_last_used(backend: Arc<FakeBackend>) -> Arc<IdleGuard> {
+        Arc::new(IdleGuard {
+            backend: Box::new(backend),
+            idle_timeout: None,
+            last_used: Mutex::new(Instant::now() - Duration::from_secs(10)),
+            running: AsyncMutex::new(true),
+        })
+    }
+
+    #[tokio::test]
+    async fn unload_if_idle_does_not_stop_a_freshly_touched_backend() {
+        let backend = Arc::new(FakeBackend {
+            running: AtomicBool::new(true),
+        });
+        let guard = guard_with_stale_last_used(backend.clone());
+
+        guard.touch();
+        guard.unload_if_idle(Duration::from_millis(50)).await;
+
+        assert!(backend.running.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn unload_if_idle_rechecks_elapsed_after_acquiring_the_lock() {
+        let backend = Arc::new(FakeBackend {
+            running: AtomicBool::new(true),
+        });
+        let guard = guard_with_stale_last_used(backend.clone());
+        let timeout = Duration::from_millis(50);
+
+        // Hold `running` ourselves to simulate `unload_if_idle` being blocked waiting for it
+        // after it has already read the stale (idle) `last_used` snapshot above.
+        let held = guard.running.lock().await;
+
+        let unloader = guard.clone();
+        let unload_task = tokio::spawn(async move { unloader.unload_if_idle(timeout).await });
+        tokio::task::yield_now().await;
+
+        // A concurrent request arrives and refreshes `last_used` while `unload_if_idle` is
+        // still waiting on the lock we're holding.
+        guard.touch();
+        drop(held);
+
+        unload_task.await.unwrap();
+
+        assert!(
+            backend.running.load(Ordering::SeqCst),
+            "a backend touched while unload_if_idle waited for the lock must not be stopped"
+        );
+    }
+
+    #[tokio::test]
+    async fn ensure_running_restarts_a_stopped_backend() {
+        let backend = Arc::new(FakeBackend {
+            running: AtomicBool::new(false),
+        });
+        let guard = Arc::new(IdleGuard {
+            backend: Box::new(backend.clone()),
+            idle_timeout: None,
+            last_used: Mutex::new(Instant::now()),
+            running: AsyncMutex::new(false),
+        });
+
+        guard.ensure_running().await;
+
+        assert!(backend.running.load(Ordering::SeqCst));
+    }
+}
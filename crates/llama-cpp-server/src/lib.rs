@@ -1,14 +1,29 @@
+mod idle;
+mod openai;
 mod supervisor;
 
-use std::{fs, path::PathBuf, sync::Arc};
+use idle::IdleGuard;
+pub use openai::serve_openai_compatible;
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock},
+    time::Duration,
+};
 
 use anyhow::Result;
 use async_trait::async_trait;
-use futures::stream::BoxStream;
+use futures::{stream::BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
 use supervisor::LlamaCppSupervisor;
+// `LocalModelEngine`, `LocalModelConfig::engine`/`idle_timeout_secs`, and `ModelConfigGroup`
+// are new additions this crate depends on; they land in `tabby_common::config` as a companion
+// change tracked alongside this series, not in this crate.
 use tabby_common::{
     api::chat::Message,
-    config::{HttpModelConfigBuilder, ModelConfig},
+    config::{HttpModelConfigBuilder, LocalModelEngine, ModelConfig, ModelConfigGroup},
     registry::{parse_model_id, ModelRegistry, GGML_MODEL_RELATIVE_PATH},
 };
 use tabby_inference::{
@@ -19,64 +34,202 @@ fn api_endpoint(port: u16) -> String {
     format!("http://127.0.0.1:{port}")
 }
 
+/// A local engine capable of serving completion/chat/embedding requests over HTTP. Lets
+/// `create_completion`, `create_chat_completion`, and `create_embedding` stay engine-agnostic:
+/// adding a new engine is a matter of implementing this trait and extending `build_backend`,
+/// without touching any of the three entry points.
+#[async_trait]
+trait LocalInferenceBackend: Send + Sync {
+    async fn start(&self);
+    fn stop(&self);
+    fn port(&self) -> u16;
+    /// The `ModelConfig` "kind" prefix used to wire up the completion/embedding clients for
+    /// this engine (e.g. `"llama.cpp"`). Chat always speaks the OpenAI schema regardless of
+    /// engine, so it doesn't consult this.
+    fn kind(&self) -> &str;
+}
+
+#[async_trait]
+impl LocalInferenceBackend for LlamaCppSupervisor {
+    async fn start(&self) {
+        LlamaCppSupervisor::start(self).await
+    }
+
+    fn stop(&self) {
+        LlamaCppSupervisor::stop(self)
+    }
+
+    fn port(&self) -> u16 {
+        LlamaCppSupervisor::port(self)
+    }
+
+    fn kind(&self) -> &str {
+        "llama.cpp"
+    }
+}
+
+fn build_backend(
+    engine: LocalModelEngine,
+    num_gpu_layers: u16,
+    model_path: &str,
+    parallelism: u8,
+    chat_template: Option<String>,
+) -> Box<dyn LocalInferenceBackend> {
+    match engine {
+        LocalModelEngine::LlamaCpp => Box::new(LlamaCppSupervisor::new(
+            "model-group",
+            num_gpu_layers,
+            true,
+            model_path,
+            parallelism,
+            chat_template,
+        )),
+    }
+}
+
+/// Identifies a local model process by the exact engine, model, and chat template it was
+/// launched to serve, so that completion/chat/embedding roles backed by the same engine,
+/// GGUF, and template can share one running server instead of spawning one each. Roles that
+/// agree on everything but the chat template get their own process rather than silently
+/// losing one role's template to whichever role happened to start the shared server first.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SupervisorKey {
+    engine: LocalModelEngine,
+    model_path: String,
+    num_gpu_layers: u16,
+    parallelism: u8,
+    chat_template: Option<String>,
+}
+
+static SUPERVISOR_POOL: OnceLock<Mutex<HashMap<SupervisorKey, Arc<IdleGuard>>>> = OnceLock::new();
+
+fn supervisor_pool() -> &'static Mutex<HashMap<SupervisorKey, Arc<IdleGuard>>> {
+    SUPERVISOR_POOL.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Returns the pooled, idle-unloadable backend for `key`, starting a new one (with the
+/// embedding endpoint enabled so the single process can answer embeddings as well as
+/// completions and chat, for engines where that applies) the first time `key` is seen. `idle_timeout` is only consulted
+/// the first time a key is seen, since all roles sharing a process also share its idle clock.
+async fn shared_supervisor(key: SupervisorKey, idle_timeout: Option<Duration>) -> Arc<IdleGuard> {
+    if let Some(guard) = supervisor_pool().lock().unwrap().get(&key) {
+        return guard.clone();
+    }
+
+    let backend = build_backend(
+        key.engine,
+        key.num_gpu_layers,
+        &key.model_path,
+        key.parallelism,
+        key.chat_template.clone(),
+    );
+    backend.start().await;
+    let guard = IdleGuard::new(backend, idle_timeout);
+    supervisor_pool()
+        .lock()
+        .unwrap()
+        .entry(key)
+        .or_insert(guard)
+        .clone()
+}
+
 struct EmbeddingServer {
-    #[allow(unused)]
-    server: LlamaCppSupervisor,
+    server: Arc<IdleGuard>,
     embedding: Arc<dyn Embedding>,
+    api_endpoint: String,
+    http: reqwest::Client,
 }
 
 impl EmbeddingServer {
-    async fn new(num_gpu_layers: u16, model_path: &str, parallelism: u8) -> EmbeddingServer {
-        let server = LlamaCppSupervisor::new(
-            "embedding",
+    async fn new(
+        engine: LocalModelEngine,
+        num_gpu_layers: u16,
+        model_path: &str,
+        parallelism: u8,
+        idle_timeout: Option<Duration>,
+    ) -> EmbeddingServer {
+        let key = SupervisorKey {
+            engine,
+            model_path: model_path.to_string(),
             num_gpu_layers,
-            true,
-            model_path,
             parallelism,
-            None,
-        );
-        server.start().await;
+            chat_template: None,
+        };
+        let server = shared_supervisor(key, idle_timeout).await;
+        let api_endpoint = api_endpoint(server.port());
 
         let config = HttpModelConfigBuilder::default()
-            .api_endpoint(api_endpoint(server.port()))
-            .kind("llama.cpp/embedding".to_string())
+            .api_endpoint(api_endpoint.clone())
+            .kind(format!("{}/embedding", server.kind()))
             .build()
             .expect("Failed to create HttpModelConfig");
 
         Self {
             server,
             embedding: http_api_bindings::create_embedding(&config).await,
+            api_endpoint,
+            http: reqwest::Client::new(),
         }
     }
 }
 
+#[derive(Serialize)]
+struct BatchEmbeddingRequest<'a> {
+    content: &'a [&'a str],
+}
+
+#[derive(Deserialize)]
+struct BatchEmbeddingEntry {
+    embedding: Vec<f32>,
+}
+
 #[async_trait]
 impl Embedding for EmbeddingServer {
     async fn embed(&self, prompt: &str) -> Result<Vec<f32>> {
+        self.server.ensure_running().await;
         self.embedding.embed(prompt).await
     }
+
+    async fn embed_batch(&self, prompts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        self.server.ensure_running().await;
+        let entries: Vec<BatchEmbeddingEntry> = self
+            .http
+            .post(format!("{}/embedding", self.api_endpoint))
+            .json(&BatchEmbeddingRequest { content: prompts })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(entries.into_iter().map(|entry| entry.embedding).collect())
+    }
 }
 
 struct CompletionServer {
-    #[allow(unused)]
-    server: LlamaCppSupervisor,
+    server: Arc<IdleGuard>,
     completion: Arc<dyn CompletionStream>,
 }
 
 impl CompletionServer {
-    async fn new(num_gpu_layers: u16, model_path: &str, parallelism: u8) -> Self {
-        let server = LlamaCppSupervisor::new(
-            "completion",
+    async fn new(
+        engine: LocalModelEngine,
+        num_gpu_layers: u16,
+        model_path: &str,
+        parallelism: u8,
+        idle_timeout: Option<Duration>,
+    ) -> Self {
+        let key = SupervisorKey {
+            engine,
+            model_path: model_path.to_string(),
             num_gpu_layers,
-            false,
-            model_path,
             parallelism,
-            None,
-        );
-        server.start().await;
+            chat_template: None,
+        };
+        let server = shared_supervisor(key, idle_timeout).await;
         let config = HttpModelConfigBuilder::default()
             .api_endpoint(api_endpoint(server.port()))
-            .kind("llama.cpp/completion".to_string())
+            .kind(format!("{}/completion", server.kind()))
             .build()
             .expect("Failed to create HttpModelConfig");
         let completion = http_api_bindings::create(&config).await;
@@ -87,32 +240,37 @@ impl CompletionServer {
 #[async_trait]
 impl CompletionStream for CompletionServer {
     async fn generate(&self, prompt: &str, options: CompletionOptions) -> BoxStream<String> {
-        self.completion.generate(prompt, options).await
+        self.server.ensure_running().await;
+        let stream = self.completion.generate(prompt, options).await;
+        // Keep touching `last_used` as chunks arrive so the idle timer doesn't stop the
+        // backend mid-stream for a generation that outlives `idle_timeout`.
+        let server = self.server.clone();
+        Box::pin(stream.inspect(move |_| server.touch()))
     }
 }
 
 struct ChatCompletionServer {
-    #[allow(unused)]
-    server: LlamaCppSupervisor,
+    server: Arc<IdleGuard>,
     chat_completion: Arc<dyn ChatCompletionStream>,
 }
 
 impl ChatCompletionServer {
     async fn new(
+        engine: LocalModelEngine,
         num_gpu_layers: u16,
         model_path: &str,
         parallelism: u8,
-        chat_template: String,
+        chat_template: Option<String>,
+        idle_timeout: Option<Duration>,
     ) -> Self {
-        let server = LlamaCppSupervisor::new(
-            "chat",
+        let key = SupervisorKey {
+            engine,
+            model_path: model_path.to_string(),
             num_gpu_layers,
-            false,
-            model_path,
             parallelism,
-            Some(chat_template),
-        );
-        server.start().await;
+            chat_template,
+        };
+        let server = shared_supervisor(key, idle_timeout).await;
         let config = HttpModelConfigBuilder::default()
             .api_endpoint(api_endpoint(server.port()))
             .kind("openai/chat".to_string())
@@ -133,55 +291,130 @@ impl ChatCompletionStream for ChatCompletionServer {
         messages: &[Message],
         options: ChatCompletionOptions,
     ) -> Result<BoxStream<String>> {
-        self.chat_completion
+        self.server.ensure_running().await;
+        let stream = self
+            .chat_completion
             .chat_completion(messages, options)
-            .await
+            .await?;
+        // Keep touching `last_used` as chunks arrive so the idle timer doesn't stop the
+        // backend mid-stream for a generation that outlives `idle_timeout`.
+        let server = self.server.clone();
+        Ok(Box::pin(stream.inspect(move |_| server.touch())))
     }
 }
 
-pub async fn create_chat_completion(
-    num_gpu_layers: u16,
-    model_path: &str,
-    parallelism: u8,
-    chat_template: String,
-) -> Arc<dyn ChatCompletionStream> {
-    Arc::new(
-        ChatCompletionServer::new(num_gpu_layers, model_path, parallelism, chat_template).await,
-    )
+/// Resolves a `ModelConfig::Local` `model_id` to the on-disk path of its GGUF file, pulling the
+/// model through the registry first if it isn't already a local path.
+async fn resolve_local_model_path(model_id: &str) -> String {
+    if fs::metadata(model_id).is_ok() {
+        PathBuf::from(model_id)
+            .join(GGML_MODEL_RELATIVE_PATH)
+            .display()
+            .to_string()
+    } else {
+        let (registry, name) = parse_model_id(model_id);
+        let registry = ModelRegistry::new(registry).await;
+        registry.get_model_path(name).display().to_string()
+    }
 }
 
-pub async fn create_completion(
-    num_gpu_layers: u16,
-    model_path: &str,
-    parallelism: u8,
-) -> Arc<dyn CompletionStream> {
-    Arc::new(CompletionServer::new(num_gpu_layers, model_path, parallelism).await)
+pub async fn create_chat_completion(config: &ModelConfig) -> Arc<dyn ChatCompletionStream> {
+    match config {
+        ModelConfig::Http(http) => http_api_bindings::create_chat(http).await,
+        ModelConfig::Local(llama) => {
+            let model_path = resolve_local_model_path(&llama.model_id).await;
+            // Normalize an unset/empty template to `None` so a chat role with no template
+            // configured keys identically to completion/embedding and can share their process,
+            // instead of `Some(String::new())` permanently keeping chat on its own server.
+            let chat_template = llama
+                .chat_template
+                .clone()
+                .filter(|template| !template.is_empty());
+            let idle_timeout = llama.idle_timeout_secs.map(Duration::from_secs);
+            Arc::new(
+                ChatCompletionServer::new(
+                    llama.engine,
+                    llama.num_gpu_layers,
+                    &model_path,
+                    llama.parallelism,
+                    chat_template,
+                    idle_timeout,
+                )
+                .await,
+            )
+        }
+    }
+}
+
+pub async fn create_completion(config: &ModelConfig) -> Arc<dyn CompletionStream> {
+    match config {
+        ModelConfig::Http(http) => http_api_bindings::create(http).await,
+        ModelConfig::Local(llama) => {
+            let model_path = resolve_local_model_path(&llama.model_id).await;
+            let idle_timeout = llama.idle_timeout_secs.map(Duration::from_secs);
+            Arc::new(
+                CompletionServer::new(
+                    llama.engine,
+                    llama.num_gpu_layers,
+                    &model_path,
+                    llama.parallelism,
+                    idle_timeout,
+                )
+                .await,
+            )
+        }
+    }
 }
 
 pub async fn create_embedding(config: &ModelConfig) -> Arc<dyn Embedding> {
     match config {
         ModelConfig::Http(http) => http_api_bindings::create_embedding(http).await,
         ModelConfig::Local(llama) => {
-            if fs::metadata(&llama.model_id).is_ok() {
-                let path = PathBuf::from(&llama.model_id);
-                let model_path = path.join(GGML_MODEL_RELATIVE_PATH);
-                Arc::new(
-                    EmbeddingServer::new(
-                        llama.num_gpu_layers,
-                        model_path.display().to_string().as_str(),
-                        llama.parallelism,
-                    )
-                    .await,
+            let model_path = resolve_local_model_path(&llama.model_id).await;
+            let idle_timeout = llama.idle_timeout_secs.map(Duration::from_secs);
+            Arc::new(
+                EmbeddingServer::new(
+                    llama.engine,
+                    llama.num_gpu_layers,
+                    &model_path,
+                    llama.parallelism,
+                    idle_timeout,
                 )
-            } else {
-                let (registry, name) = parse_model_id(&llama.model_id);
-                let registry = ModelRegistry::new(registry).await;
-                let model_path = registry.get_model_path(name).display().to_string();
-                Arc::new(
-                    EmbeddingServer::new(llama.num_gpu_layers, &model_path, llama.parallelism)
-                        .await,
-                )
-            }
+                .await,
+            )
         }
     }
 }
+
+/// The trio of model handles `create_model_group` resolves a `ModelConfigGroup` into. Each
+/// field is only populated when the corresponding role is configured.
+#[derive(Clone)]
+pub struct ModelGroup {
+    pub completion: Option<Arc<dyn CompletionStream>>,
+    pub chat: Option<Arc<dyn ChatCompletionStream>>,
+    pub embedding: Option<Arc<dyn Embedding>>,
+}
+
+/// Resolves all roles configured in `config`. Local roles that resolve to the identical
+/// `SupervisorKey` (engine, model, GPU/parallelism settings, and chat template) share a
+/// single backend via `SUPERVISOR_POOL` rather than each spawning their own.
+pub async fn create_model_group(config: &ModelConfigGroup) -> ModelGroup {
+    let completion = match &config.completion {
+        Some(completion) => Some(create_completion(completion).await),
+        None => None,
+    };
+    let chat = match &config.chat {
+        Some(chat) => Some(create_chat_completion(chat).await),
+        None => None,
+    };
+    let embedding = match &config.embedding {
+        Some(embedding) => Some(create_embedding(embedding).await),
+        None => None,
+    };
+
+    ModelGroup {
+        completion,
+        chat,
+        embedding,
+    }
+}
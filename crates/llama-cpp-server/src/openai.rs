@@ -0,0 +1,455 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::post,
+    Json, Router,
+};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tabby_common::api::chat::Message;
+use tabby_inference::{ChatCompletionOptions, CompletionOptions};
+
+use crate::ModelGroup;
+
+#[derive(Clone)]
+struct OpenAiState {
+    group: Arc<ModelGroup>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+enum StopSequences {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl From<StopSequences> for Vec<String> {
+    fn from(stop: StopSequences) -> Self {
+        match stop {
+            StopSequences::One(s) => vec![s],
+            StopSequences::Many(v) => v,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CompletionRequest {
+    model: String,
+    prompt: String,
+    #[serde(default)]
+    stream: bool,
+    max_tokens: Option<i32>,
+    stop: Option<StopSequences>,
+}
+
+impl CompletionRequest {
+    fn options(&self) -> CompletionOptions {
+        let mut options = CompletionOptions::default();
+        if let Some(max_tokens) = self.max_tokens {
+            options.max_decoding_tokens = max_tokens;
+        }
+        if let Some(stop) = self.stop.clone() {
+            options.stop_words = stop.into();
+        }
+        options
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct CompletionChoice {
+    text: String,
+    index: u32,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct CompletionResponse {
+    id: String,
+    object: &'static str,
+    model: String,
+    choices: Vec<CompletionChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    role: String,
+    content: String,
+}
+
+#[derive(Deserialize)]
+struct ChatCompletionRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    stream: bool,
+    max_tokens: Option<i32>,
+    stop: Option<StopSequences>,
+}
+
+impl ChatCompletionRequest {
+    fn options(&self) -> ChatCompletionOptions {
+        let mut options = ChatCompletionOptions::default();
+        if let Some(max_tokens) = self.max_tokens {
+            options.max_decoding_tokens = max_tokens;
+        }
+        if let Some(stop) = self.stop.clone() {
+            options.stop_words = stop.into();
+        }
+        options
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct ChatCompletionResponseMessage {
+    role: &'static str,
+    content: String,
+}
+
+#[derive(Serialize, Clone)]
+struct ChatCompletionChoice {
+    index: u32,
+    message: ChatCompletionResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct ChatCompletionResponse {
+    id: String,
+    object: &'static str,
+    model: String,
+    choices: Vec<ChatCompletionChoice>,
+}
+
+#[derive(Serialize, Clone)]
+struct ChatCompletionChunkDelta {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct ChatCompletionChunkChoice {
+    index: u32,
+    delta: ChatCompletionChunkDelta,
+    finish_reason: Option<String>,
+}
+
+#[derive(Serialize, Clone)]
+struct ChatCompletionChunk {
+    id: String,
+    object: &'static str,
+    model: String,
+    choices: Vec<ChatCompletionChunkChoice>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsRequest {
+    model: String,
+    input: EmbeddingInput,
+}
+
+#[derive(Serialize)]
+struct EmbeddingData {
+    object: &'static str,
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+#[derive(Serialize)]
+struct EmbeddingsResponse {
+    object: &'static str,
+    model: String,
+    data: Vec<EmbeddingData>,
+}
+
+fn sse_event<T: Serialize>(chunk: &T) -> Result<Event, Infallible> {
+    Ok(Event::default().data(serde_json::to_string(chunk).expect("chunk is always valid JSON")))
+}
+
+fn sse_done() -> Result<Event, Infallible> {
+    Ok(Event::default().data("[DONE]"))
+}
+
+async fn completions(
+    State(state): State<OpenAiState>,
+    Json(req): Json<CompletionRequest>,
+) -> Response {
+    let Some(completion) = &state.group.completion else {
+        return (StatusCode::NOT_IMPLEMENTED, "no completion model configured").into_response();
+    };
+
+    let mut stream = completion.generate(&req.prompt, req.options()).await;
+
+    if req.stream {
+        let model = req.model.clone();
+        let body = stream
+            .enumerate()
+            .map(move |(i, text)| {
+                sse_event(&CompletionResponse {
+                    id: format!("cmpl-{i}"),
+                    object: "text_completion",
+                    model: model.clone(),
+                    choices: vec![CompletionChoice {
+                        text,
+                        index: 0,
+                        finish_reason: None,
+                    }],
+                })
+            })
+            .chain(futures::stream::once(async { sse_done() }));
+        Sse::new(body).keep_alive(KeepAlive::default()).into_response()
+    } else {
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            text.push_str(&chunk);
+        }
+        Json(CompletionResponse {
+            id: "cmpl-0".into(),
+            object: "text_completion",
+            model: req.model,
+            choices: vec![CompletionChoice {
+                text,
+                index: 0,
+                finish_reason: Some("stop".into()),
+            }],
+        })
+        .into_response()
+    }
+}
+
+async fn chat_completions(
+    State(state): State<OpenAiState>,
+    Json(req): Json<ChatCompletionRequest>,
+) -> Response {
+    let Some(chat) = &state.group.chat else {
+        return (StatusCode::NOT_IMPLEMENTED, "no chat model configured").into_response();
+    };
+
+    let messages: Vec<Message> = req
+        .messages
+        .into_iter()
+        .map(|m| Message {
+            role: m.role,
+            content: m.content,
+        })
+        .collect();
+
+    let result = chat.chat_completion(&messages, req.options()).await;
+    let mut stream = match result {
+        Ok(stream) => stream,
+        Err(err) => return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    };
+
+    if req.stream {
+        let model = req.model.clone();
+        let mut first = true;
+        let body = stream
+            .enumerate()
+            .map(move |(i, text)| {
+                let role = if first { Some("assistant") } else { None };
+                first = false;
+                sse_event(&ChatCompletionChunk {
+                    id: format!("chatcmpl-{i}"),
+                    object: "chat.completion.chunk",
+                    model: model.clone(),
+                    choices: vec![ChatCompletionChunkChoice {
+                        index: 0,
+                        delta: ChatCompletionChunkDelta {
+                            role,
+                            content: Some(text),
+                        },
+                        finish_reason: None,
+                    }],
+                })
+            })
+            .chain(futures::stream::once(async { sse_done() }));
+        Sse::new(body).keep_alive(KeepAlive::default()).into_response()
+    } else {
+        let mut content = String::new();
+        while let Some(chunk) = stream.next().await {
+            content.push_str(&chunk);
+        }
+        Json(ChatCompletionResponse {
+            id: "chatcmpl-0".into(),
+            object: "chat.completion",
+            model: req.model,
+            choices: vec![ChatCompletionChoice {
+                index: 0,
+                message: ChatCompletionResponseMessage {
+                    role: "assistant",
+                    content,
+                },
+                finish_reason: Some("stop".into()),
+            }],
+        })
+        .into_response()
+    }
+}
+
+async fn embeddings(
+    State(state): State<OpenAiState>,
+    Json(req): Json<EmbeddingsRequest>,
+) -> Response {
+    let Some(embedding) = &state.group.embedding else {
+        return (StatusCode::NOT_IMPLEMENTED, "no embedding model configured").into_response();
+    };
+
+    let inputs = match req.input {
+        EmbeddingInput::One(s) => vec![s],
+        EmbeddingInput::Many(v) => v,
+    };
+
+    let data = if inputs.len() > 1 {
+        let refs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        match embedding.embed_batch(&refs).await {
+            Ok(vectors) => vectors
+                .into_iter()
+                .enumerate()
+                .map(|(index, embedding)| EmbeddingData {
+                    object: "embedding",
+                    embedding,
+                    index,
+                })
+                .collect(),
+            Err(err) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+            }
+        }
+    } else {
+        let mut data = Vec::with_capacity(inputs.len());
+        for (index, input) in inputs.into_iter().enumerate() {
+            match embedding.embed(&input).await {
+                Ok(vector) => data.push(EmbeddingData {
+                    object: "embedding",
+                    embedding: vector,
+                    index,
+                }),
+                Err(err) => {
+                    return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+                }
+            }
+        }
+        data
+    };
+
+    Json(EmbeddingsResponse {
+        object: "list",
+        model: req.model,
+        data,
+    })
+    .into_response()
+}
+
+fn router(group: ModelGroup) -> Router {
+    let state = OpenAiState {
+        group: Arc::new(group),
+    };
+    Router::new()
+        .route("/v1/completions", post(completions))
+        .route("/v1/chat/completions", post(chat_completions))
+        .route("/chat/completions", post(chat_completions))
+        .route("/v1/embeddings", post(embeddings))
+        .with_state(state)
+}
+
+/// Serves `group`'s configured models behind an OpenAI-compatible HTTP API, so other tools
+/// can target a running Tabby instance as a drop-in OpenAI endpoint.
+pub async fn serve_openai_compatible(addr: SocketAddr, group: ModelGroup) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router(group)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[test]
+    fn completion_request_options_maps_max_tokens_and_stop() {
+        let req: CompletionRequest = serde_json::from_str(
+            r#"{"model": "m", "prompt": "hi", "max_tokens": 42, "stop": "\n"}"#,
+        )
+        .unwrap();
+
+        let options = req.options();
+        assert_eq!(options.max_decoding_tokens, 42);
+        assert_eq!(options.stop_words, vec!["\n".to_string()]);
+    }
+
+    #[test]
+    fn completion_request_options_accepts_stop_as_array() {
+        let req: CompletionRequest =
+            serde_json::from_str(r#"{"model": "m", "prompt": "hi", "stop": ["a", "b"]}"#).unwrap();
+
+        let options = req.options();
+        assert_eq!(options.stop_words, vec!["a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn completion_request_options_defaults_when_unset() {
+        let req: CompletionRequest =
+            serde_json::from_str(r#"{"model": "m", "prompt": "hi"}"#).unwrap();
+
+        let default = CompletionOptions::default();
+        let options = req.options();
+        assert_eq!(options.max_decoding_tokens, default.max_decoding_tokens);
+        assert!(options.stop_words.is_empty());
+    }
+
+    #[test]
+    fn chat_completion_request_options_maps_max_tokens_and_stop() {
+        let req: ChatCompletionRequest = serde_json::from_str(
+            r#"{"model": "m", "messages": [], "max_tokens": 7, "stop": ["<|end|>"]}"#,
+        )
+        .unwrap();
+
+        let options = req.options();
+        assert_eq!(options.max_decoding_tokens, 7);
+        assert_eq!(options.stop_words, vec!["<|end|>".to_string()]);
+    }
+
+    fn empty_group() -> ModelGroup {
+        ModelGroup {
+            completion: None,
+            chat: None,
+            embedding: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_completions_alias_routes_like_the_versioned_path() {
+        for path in ["/v1/chat/completions", "/chat/completions"] {
+            let response = router(empty_group())
+                .oneshot(
+                    axum::http::Request::builder()
+                        .method("POST")
+                        .uri(path)
+                        .header("content-type", "application/json")
+                        .body(Body::from(r#"{"model": "m", "messages": []}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED, "path: {path}");
+        }
+    }
+}